@@ -0,0 +1,235 @@
+//! Chromaprint-style acoustic fingerprinting, used to find perceptually
+//! identical audio files regardless of their container format or encoding.
+//!
+//! The signal is downmixed to mono and decimated to a low sample rate, then
+//! a short-time FFT is slid across it with heavy overlap. Each spectrum is
+//! folded into a handful of logarithmically-spaced bands covering the
+//! musical range, and one bit per band is set from the sign of the
+//! second-order difference in band energy across time and frequency. The
+//! bits for a frame are packed into a single `u32`, so two fingerprints can
+//! be compared frame-by-frame with a popcount of their XOR.
+
+use aus::AudioFile;
+use rustfft::{num_complex::Complex, FftPlanner};
+use std::f64::consts::PI;
+
+/// Lowest and highest edge, in Hz, of the band-folding range. This roughly
+/// covers the fundamental range of musical instruments and voice.
+const LOWEST_BAND_HZ: f64 = 250.0;
+const HIGHEST_BAND_HZ: f64 = 2000.0;
+
+/// Parameters controlling how a fingerprint is computed and compared.
+#[derive(Clone, clap::Args)]
+pub struct FingerprintConfig {
+    /// Sample rate (Hz) duplicate-detection fingerprints are computed at.
+    #[arg(id = "fingerprint_sample_rate", long = "fingerprint-rate", default_value_t = 11025)]
+    pub fingerprint_sample_rate: u32,
+
+    /// FFT frame size used when fingerprinting.
+    #[arg(long = "frame-size", default_value_t = 4096)]
+    pub frame_size: usize,
+
+    /// Number of logarithmically-spaced frequency bands to fold the spectrum into.
+    #[arg(long = "bands", default_value_t = 12)]
+    pub num_bands: usize,
+
+    /// Maximum normalized Hamming distance for two files to count as duplicates.
+    #[arg(long = "threshold", default_value_t = 0.35)]
+    pub match_threshold: f64
+}
+
+/// A compact acoustic fingerprint: one `u32` of packed band bits per frame.
+pub struct Fingerprint {
+    pub frames: Vec<u32>,
+}
+
+/// Computes a fingerprint for the decoded audio, or `None` if the file is
+/// too short to produce two analysis frames (each fingerprint frame is
+/// derived from a pair of consecutive spectra).
+pub fn fingerprint(audio: &AudioFile, config: &FingerprintConfig) -> Option<Fingerprint> {
+    let source_rate = if audio.sample_rate == 0 { 44100 } else { audio.sample_rate };
+    let mono = downmix_to_mono(audio);
+    let resampled = decimate_to_rate(&mono, source_rate, config.fingerprint_sample_rate);
+    if resampled.len() < config.frame_size {
+        return None;
+    }
+
+    let hop = (config.frame_size / 3).max(1);
+    let band_energies = band_energies(&resampled, config.frame_size, hop, config.num_bands, config.fingerprint_sample_rate);
+    if band_energies.len() < 2 {
+        return None;
+    }
+
+    let num_bits = config.num_bands.min(32);
+    let mut frames = Vec::with_capacity(band_energies.len() - 1);
+    for t in 1..band_energies.len() {
+        let mut bits: u32 = 0;
+        for band in 0..num_bits {
+            let next_band = (band + 1).min(config.num_bands - 1);
+            let gradient = (band_energies[t][band] - band_energies[t][next_band])
+                - (band_energies[t - 1][band] - band_energies[t - 1][next_band]);
+            if gradient > 0.0 {
+                bits |= 1 << band;
+            }
+        }
+        frames.push(bits);
+    }
+    Some(Fingerprint { frames })
+}
+
+/// Compares two fingerprints by sliding the shorter one over the longer one
+/// and returning the minimum Hamming distance found, normalized to [0, 1]
+/// by the number of bits compared. The bits compared per frame are the bit
+/// positions up to the highest bit set in either fingerprint, so
+/// fingerprints built from fewer than 32 bands still span the full range.
+pub fn compare(a: &Fingerprint, b: &Fingerprint) -> f64 {
+    if a.frames.is_empty() || b.frames.is_empty() {
+        return 1.0;
+    }
+    let used_bits = a.frames.iter().chain(b.frames.iter()).fold(0u32, |acc, &frame| acc | frame);
+    let bits_per_frame = 32 - used_bits.leading_zeros();
+    if bits_per_frame == 0 {
+        return 0.0;
+    }
+    let (shorter, longer) = if a.frames.len() <= b.frames.len() { (a, b) } else { (b, a) };
+
+    let mut best_distance = 1.0;
+    for offset in 0..longer.frames.len() {
+        let overlap = shorter.frames.len().min(longer.frames.len() - offset);
+        if overlap == 0 {
+            break;
+        }
+        let mismatched_bits: u32 = (0..overlap)
+            .map(|i| (shorter.frames[i] ^ longer.frames[offset + i]).count_ones())
+            .sum();
+        let normalized = mismatched_bits as f64 / (overlap as f64 * bits_per_frame as f64);
+        if normalized < best_distance {
+            best_distance = normalized;
+        }
+    }
+    best_distance
+}
+
+/// Whether two fingerprints are close enough to be considered duplicates.
+pub fn is_duplicate(a: &Fingerprint, b: &Fingerprint, threshold: f64) -> bool {
+    compare(a, b) <= threshold
+}
+
+fn downmix_to_mono(audio: &AudioFile) -> Vec<f64> {
+    let num_channels = audio.samples.len().max(1);
+    let num_frames = audio.samples.first().map(|channel| channel.len()).unwrap_or(0);
+    let mut mono = vec![0.0; num_frames];
+    for channel in audio.samples.iter() {
+        for (i, sample) in channel.iter().enumerate() {
+            mono[i] += sample / num_channels as f64;
+        }
+    }
+    mono
+}
+
+/// Naive decimation to a lower sample rate. This is good enough for
+/// fingerprinting purposes, where only coarse spectral shape matters.
+fn decimate_to_rate(samples: &[f64], source_rate: u32, target_rate: u32) -> Vec<f64> {
+    if source_rate <= target_rate || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let ratio = source_rate as f64 / target_rate as f64;
+    let out_len = (samples.len() as f64 / ratio).floor() as usize;
+    let mut out = Vec::with_capacity(out_len);
+    for i in 0..out_len {
+        let src_index = ((i as f64 * ratio) as usize).min(samples.len() - 1);
+        out.push(samples[src_index]);
+    }
+    out
+}
+
+fn hann_window(len: usize) -> Vec<f64> {
+    if len <= 1 {
+        return vec![1.0; len];
+    }
+    (0..len)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f64 / (len as f64 - 1.0)).cos())
+        .collect()
+}
+
+/// Maps FFT bin indices onto `num_bands` logarithmically-spaced bands
+/// between `LOWEST_BAND_HZ` and `HIGHEST_BAND_HZ`.
+fn band_bin_edges(num_bands: usize, frame_size: usize, sample_rate: u32) -> Vec<usize> {
+    let hz_per_bin = sample_rate as f64 / frame_size as f64;
+    let log_low = LOWEST_BAND_HZ.ln();
+    let log_high = HIGHEST_BAND_HZ.ln();
+    (0..=num_bands)
+        .map(|i| {
+            let frac = i as f64 / num_bands as f64;
+            let hz = (log_low + frac * (log_high - log_low)).exp();
+            ((hz / hz_per_bin) as usize).min(frame_size / 2)
+        })
+        .collect()
+}
+
+fn band_energies(samples: &[f64], frame_size: usize, hop: usize, num_bands: usize, sample_rate: u32) -> Vec<Vec<f64>> {
+    let window = hann_window(frame_size);
+    let edges = band_bin_edges(num_bands, frame_size, sample_rate);
+
+    let mut planner = FftPlanner::<f64>::new();
+    let fft = planner.plan_fft_forward(frame_size);
+
+    let mut energies = Vec::new();
+    let mut start = 0;
+    while start + frame_size <= samples.len() {
+        let mut buffer: Vec<Complex<f64>> = samples[start..start + frame_size]
+            .iter()
+            .zip(window.iter())
+            .map(|(sample, w)| Complex::new(sample * w, 0.0))
+            .collect();
+        fft.process(&mut buffer);
+
+        let mut bands = vec![0.0; num_bands];
+        for (band, window_edges) in edges.windows(2).enumerate() {
+            let (lo, hi) = (window_edges[0], window_edges[1].max(window_edges[0] + 1));
+            let mut energy = 0.0;
+            for bin in lo..hi.min(buffer.len()) {
+                energy += buffer[bin].norm_sqr();
+            }
+            bands[band] = energy;
+        }
+        energies.push(bands);
+        start += hop;
+    }
+    energies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_identical_fingerprints_is_zero_distance() {
+        let a = Fingerprint { frames: vec![0b1010, 0b0101, 0b1111] };
+        let b = Fingerprint { frames: vec![0b1010, 0b0101, 0b1111] };
+        assert_eq!(compare(&a, &b), 0.0);
+    }
+
+    #[test]
+    fn compare_finds_best_alignment_when_sliding() {
+        let a = Fingerprint { frames: vec![0b1010, 0b0101] };
+        let b = Fingerprint { frames: vec![0b1111, 0b1010, 0b0101] };
+        assert_eq!(compare(&a, &b), 0.0);
+    }
+
+    #[test]
+    fn compare_of_fully_mismatched_frames_is_one() {
+        let a = Fingerprint { frames: vec![0x0000_0000] };
+        let b = Fingerprint { frames: vec![0xFFFF_FFFF] };
+        assert_eq!(compare(&a, &b), 1.0);
+    }
+
+    #[test]
+    fn is_duplicate_respects_threshold() {
+        let a = Fingerprint { frames: vec![0b1010] };
+        let b = Fingerprint { frames: vec![0b1011] };
+        let distance = compare(&a, &b);
+        assert!(is_duplicate(&a, &b, distance));
+        assert!(!is_duplicate(&a, &b, distance - 0.01));
+    }
+}
@@ -0,0 +1,247 @@
+//! ITU-R BS.1770 style integrated loudness measurement and gain
+//! computation, used by the `--normalize` mode to bring converted files to
+//! a consistent playback level.
+
+/// Which group of files a loudness gain is computed across.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum NormalizeMode {
+    Track,
+    Album,
+    Off
+}
+
+/// Whether a computed gain is baked into the samples or left for a player
+/// to apply from a tag.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum GainMode {
+    Apply,
+    Tag
+}
+
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_OFFSET_LU: f64 = 10.0;
+const BLOCK_SECONDS: f64 = 0.4;
+const BLOCK_OVERLAP: f64 = 0.75;
+
+pub const TARGET_TRACK_LUFS: f64 = -18.0;
+pub const TARGET_R128_LUFS: f64 = -23.0;
+
+/// Which default integrated-loudness target `--normalize` aims for when
+/// `--target-lufs` isn't given explicitly.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum LoudnessPreset {
+    Track,
+    R128
+}
+
+impl LoudnessPreset {
+    pub fn target_lufs(self) -> f64 {
+        match self {
+            LoudnessPreset::Track => TARGET_TRACK_LUFS,
+            LoudnessPreset::R128 => TARGET_R128_LUFS
+        }
+    }
+}
+
+/// Measures the ITU-R BS.1770 integrated loudness, in LUFS, of a
+/// multichannel signal. Every channel is unity-weighted: the BS.1770
+/// surround-channel weights are not applied, so this is exact for mono and
+/// stereo input only.
+pub fn integrated_loudness(channels: &Vec<Vec<f64>>, sample_rate: u32) -> f64 {
+    if channels.is_empty() || sample_rate == 0 {
+        return ABSOLUTE_GATE_LUFS;
+    }
+    let weighted: Vec<Vec<f64>> = channels.iter().map(|channel| k_weight(channel, sample_rate)).collect();
+
+    let block_len = (BLOCK_SECONDS * sample_rate as f64).round() as usize;
+    let hop = (((1.0 - BLOCK_OVERLAP) * block_len as f64).round() as usize).max(1);
+    let num_frames = weighted.first().map(|channel| channel.len()).unwrap_or(0);
+    if block_len == 0 || num_frames < block_len {
+        return ABSOLUTE_GATE_LUFS;
+    }
+
+    let mut block_loudness = Vec::new();
+    let mut start = 0;
+    while start + block_len <= num_frames {
+        let mut power_sum = 0.0;
+        for channel in weighted.iter() {
+            let mean_square: f64 = channel[start..start + block_len].iter().map(|s| s * s).sum::<f64>() / block_len as f64;
+            power_sum += mean_square;
+        }
+        block_loudness.push(power_to_lufs(power_sum));
+        start += hop;
+    }
+
+    let gated: Vec<f64> = block_loudness.iter().copied().filter(|&l| l > ABSOLUTE_GATE_LUFS).collect();
+    if gated.is_empty() {
+        return ABSOLUTE_GATE_LUFS;
+    }
+
+    let mean_power: f64 = gated.iter().map(|&l| lufs_to_power(l)).sum::<f64>() / gated.len() as f64;
+    let relative_gate = power_to_lufs(mean_power) - RELATIVE_GATE_OFFSET_LU;
+
+    let above_relative: Vec<f64> = gated.iter().copied().filter(|&l| l > relative_gate).collect();
+    if above_relative.is_empty() {
+        return relative_gate;
+    }
+    let integrated_power: f64 = above_relative.iter().map(|&l| lufs_to_power(l)).sum::<f64>() / above_relative.len() as f64;
+    power_to_lufs(integrated_power)
+}
+
+/// Gain, in dB, needed to bring `measured_lufs` to `target_lufs`.
+pub fn gain_db(measured_lufs: f64, target_lufs: f64) -> f64 {
+    target_lufs - measured_lufs
+}
+
+/// Applies a gain (in dB) to every sample in place, clamping to avoid
+/// wrapping past full scale.
+pub fn apply_gain(channels: &mut Vec<Vec<f64>>, gain_db: f64) {
+    let factor = 10f64.powf(gain_db / 20.0);
+    for channel in channels.iter_mut() {
+        for sample in channel.iter_mut() {
+            *sample = (*sample * factor).clamp(-1.0, 1.0);
+        }
+    }
+}
+
+/// Combines several files' integrated loudness values into one "album"
+/// loudness by averaging their powers. This approximates re-gating a
+/// concatenation of the files without needing to decode them all at once.
+pub fn combine_lufs(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return ABSOLUTE_GATE_LUFS;
+    }
+    let mean_power: f64 = values.iter().map(|&l| lufs_to_power(l)).sum::<f64>() / values.len() as f64;
+    power_to_lufs(mean_power)
+}
+
+fn lufs_to_power(lufs: f64) -> f64 {
+    10f64.powf((lufs + 0.691) / 10.0)
+}
+
+fn power_to_lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+/// Two-stage K-weighting pre-filter: a high-shelf boost above ~1.5 kHz
+/// followed by a high-pass around 38 Hz, per the BS.1770 reference design.
+fn k_weight(samples: &Vec<f64>, sample_rate: u32) -> Vec<f64> {
+    let shelf = high_shelf_biquad(sample_rate as f64, 1500.0, 4.0);
+    let highpass = high_pass_biquad(sample_rate as f64, 38.0);
+    let stage1 = apply_biquad(samples, &shelf);
+    apply_biquad(&stage1, &highpass)
+}
+
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64
+}
+
+fn apply_biquad(samples: &Vec<f64>, coeffs: &Biquad) -> Vec<f64> {
+    let mut out = Vec::with_capacity(samples.len());
+    let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+    for &x0 in samples.iter() {
+        let y0 = coeffs.b0 * x0 + coeffs.b1 * x1 + coeffs.b2 * x2 - coeffs.a1 * y1 - coeffs.a2 * y2;
+        out.push(y0);
+        x2 = x1;
+        x1 = x0;
+        y2 = y1;
+        y1 = y0;
+    }
+    out
+}
+
+/// RBJ Audio EQ Cookbook high-shelf, with shelf slope S = 1.
+fn high_shelf_biquad(sample_rate: f64, freq: f64, gain_db: f64) -> Biquad {
+    let a = 10f64.powf(gain_db / 40.0);
+    let omega = 2.0 * std::f64::consts::PI * freq / sample_rate;
+    let (sn, cs) = (omega.sin(), omega.cos());
+    let alpha = sn / 2.0 * 2f64.sqrt();
+    let sqrt_a = a.sqrt();
+
+    let a0 = (a + 1.0) - (a - 1.0) * cs + 2.0 * sqrt_a * alpha;
+    Biquad {
+        b0: (a * ((a + 1.0) + (a - 1.0) * cs + 2.0 * sqrt_a * alpha)) / a0,
+        b1: (-2.0 * a * ((a - 1.0) + (a + 1.0) * cs)) / a0,
+        b2: (a * ((a + 1.0) + (a - 1.0) * cs - 2.0 * sqrt_a * alpha)) / a0,
+        a1: (2.0 * ((a - 1.0) - (a + 1.0) * cs)) / a0,
+        a2: ((a + 1.0) - (a - 1.0) * cs - 2.0 * sqrt_a * alpha) / a0
+    }
+}
+
+/// RBJ Audio EQ Cookbook high-pass, with Q = 1/sqrt(2) (Butterworth).
+fn high_pass_biquad(sample_rate: f64, freq: f64) -> Biquad {
+    let q = std::f64::consts::FRAC_1_SQRT_2;
+    let omega = 2.0 * std::f64::consts::PI * freq / sample_rate;
+    let (sn, cs) = (omega.sin(), omega.cos());
+    let alpha = sn / (2.0 * q);
+
+    let a0 = 1.0 + alpha;
+    Biquad {
+        b0: ((1.0 + cs) / 2.0) / a0,
+        b1: (-(1.0 + cs)) / a0,
+        b2: ((1.0 + cs) / 2.0) / a0,
+        a1: (-2.0 * cs) / a0,
+        a2: (1.0 - alpha) / a0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integrated_loudness_of_silence_hits_absolute_gate() {
+        let silence = vec![vec![0.0; 48000]];
+        assert_eq!(integrated_loudness(&silence, 48000), ABSOLUTE_GATE_LUFS);
+    }
+
+    #[test]
+    fn integrated_loudness_of_a_loud_tone_clears_the_absolute_gate() {
+        let sample_rate = 48000;
+        let tone: Vec<f64> = (0..sample_rate)
+            .map(|i| (2.0 * std::f64::consts::PI * 1000.0 * i as f64 / sample_rate as f64).sin())
+            .collect();
+        let loudness = integrated_loudness(&vec![tone], sample_rate as u32);
+        assert!(loudness > ABSOLUTE_GATE_LUFS);
+    }
+
+    #[test]
+    fn integrated_loudness_of_too_short_signal_hits_absolute_gate() {
+        let short = vec![vec![1.0; 10]];
+        assert_eq!(integrated_loudness(&short, 48000), ABSOLUTE_GATE_LUFS);
+    }
+
+    #[test]
+    fn gain_db_is_the_difference_between_target_and_measured() {
+        assert_eq!(gain_db(-23.0, -18.0), 5.0);
+        assert_eq!(gain_db(-10.0, -18.0), -8.0);
+    }
+
+    #[test]
+    fn combine_lufs_of_equal_values_returns_that_value() {
+        assert!((combine_lufs(&[-18.0, -18.0, -18.0]) - (-18.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn combine_lufs_of_no_values_hits_absolute_gate() {
+        assert_eq!(combine_lufs(&[]), ABSOLUTE_GATE_LUFS);
+    }
+
+    #[test]
+    fn apply_gain_clamps_to_full_scale() {
+        let mut channels = vec![vec![0.9, -0.9]];
+        apply_gain(&mut channels, 12.0);
+        assert_eq!(channels[0][0], 1.0);
+        assert_eq!(channels[0][1], -1.0);
+    }
+
+    #[test]
+    fn loudness_preset_targets_match_the_documented_defaults() {
+        assert_eq!(LoudnessPreset::Track.target_lufs(), TARGET_TRACK_LUFS);
+        assert_eq!(LoudnessPreset::R128.target_lufs(), TARGET_R128_LUFS);
+    }
+}
@@ -1,14 +1,36 @@
 use aus::AudioError;
+use clap::Parser;
 use glob::glob;
 use threadpool::ThreadPool;
 
+mod fingerprint;
+use fingerprint::FingerprintConfig;
+mod resample;
+use resample::{BitDepth, ChannelLayout};
+mod metadata;
+mod loudness;
+use loudness::{NormalizeMode, GainMode, LoudnessPreset};
+mod naming;
+use naming::Rewrite;
+mod progress;
+
+/// The file extensions scanned for when `--ext` isn't given.
+const DEFAULT_EXTENSIONS: [&str; 8] = ["aif", "aiff", "mp3", "flac", "ogg", "aac", "m4a", "wma"];
+
 /// Finds all audio files that match a search pattern.
 /// For each file, returns a tuple that has the full path, directory, and file name without extension.
-fn find_audio(directory: &str) -> Vec<(String, String, String)> {
+/// `recurse` selects between scanning every subdirectory (`**/*.ext`) and just one level deep
+/// (`*/*.ext`); paths matching any of `excludes` (as glob patterns) are left out.
+fn find_audio(directory: &str, extensions: &Vec<String>, recurse: bool, excludes: &Vec<String>) -> Vec<(String, String, String)> {
     let mut file_paths: Vec<(String, String, String)> = Vec::new();
-    let extensions = vec!["aif", "aiff", "mp3", "flac", "ogg", "aac", "m4a", "wma"];
+    let exclude_patterns: Vec<glob::Pattern> = excludes.iter().filter_map(|pattern| glob::Pattern::new(pattern).ok()).collect();
     for extension in extensions {
-        let entries = glob(&format!("{}/**/*.{}", directory, extension));
+        let search_pattern = if recurse {
+            format!("{}/**/*.{}", directory, extension)
+        } else {
+            format!("{}/*/*.{}", directory, extension)
+        };
+        let entries = glob(&search_pattern);
         match entries {
             Ok(paths) => {
                 for entry in paths {
@@ -31,6 +53,9 @@ fn find_audio(directory: &str) -> Vec<(String, String, String)> {
                                 Some(x) => String::from(x),
                                 None => String::from("")
                             };
+                            if exclude_patterns.iter().any(|pattern| pattern.matches(&path)) {
+                                continue;
+                            }
                             file_paths.push((path, parent, file_name));
                         },
                         Err(_) => ()
@@ -43,8 +68,40 @@ fn find_audio(directory: &str) -> Vec<(String, String, String)> {
     file_paths
 }
 
-/// Processes all of the files in a file vector and converts them to WAV
-fn process(files: &Vec<(String, String, String)>, max_num_threads: usize) {
+/// The transcoding options that apply to every file in a batch: the output
+/// bit depth/sample format, sample rate, and channel layout. `None` means
+/// "leave this attribute alone" (aside from the zero-value defaults that
+/// already applied before this existed).
+#[derive(Clone)]
+struct TranscodeOptions {
+    bit_depth: Option<BitDepth>,
+    sample_rate: Option<u32>,
+    channels: ChannelLayout,
+    strip_tags: bool,
+    normalize_gains: Option<std::sync::Arc<std::collections::HashMap<String, f64>>>,
+    gain_mode: GainMode,
+    name_pattern: String,
+    rewrite: Option<Rewrite>
+}
+
+/// Formats an `AudioError` the same way across every call site.
+fn audio_error_message(err: &AudioError) -> String {
+    match err {
+        AudioError::FileCorrupt => "the file was corrupt".to_string(),
+        AudioError::FileInaccessible(msg) => format!("the file was inaccessible ({})", msg),
+        AudioError::NumChannels(msg) => format!("the number of channels was wrong ({})", msg),
+        AudioError::NumFrames(msg) => format!("the number of frames was wrong ({})", msg),
+        AudioError::SampleValueOutOfRange(msg) => format!("a sample value was out of range ({})", msg),
+        AudioError::WrongFormat(msg) => format!("the format was wrong ({})", msg)
+    }
+}
+
+/// Processes all of the files in a file vector and converts them to WAV,
+/// applying `options` to resample, re-quantize, and remix as requested.
+/// Progress is tracked with atomics rather than per-file `println!`s: a
+/// background reporter thread prints a periodic status line, and the
+/// returned `Summary` lists every failure once the batch has drained.
+fn process(files: &Vec<(String, String, String)>, max_num_threads: usize, options: TranscodeOptions) -> progress::Summary {
     let max_available_threads = match std::thread::available_parallelism() {
         Ok(x) => x.get(),
         Err(_) => 1
@@ -56,13 +113,18 @@ fn process(files: &Vec<(String, String, String)>, max_num_threads: usize) {
         usize::min(max_available_threads, max_num_threads)
     };
 
+    let progress = progress::Progress::new(files.len() as u64);
+    let reporter_stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let reporter = progress.spawn_reporter(reporter_stop.clone());
+
     let pool = ThreadPool::new(num_threads);
-    for file_tup in files.iter() {
+    for (index, file_tup) in files.iter().enumerate() {
         let file = file_tup.0.clone();
         let dir = file_tup.1.clone();
         let name = file_tup.2.clone();
+        let options = options.clone();
+        let progress = progress.clone();
         pool.execute(move || {
-            println!("File: {}", file);
             match aus::read(&file) {
                 Ok(mut audio) => {
                     if audio.bits_per_sample == 0 {
@@ -72,93 +134,327 @@ fn process(files: &Vec<(String, String, String)>, max_num_threads: usize) {
                     if audio.sample_rate == 0 {
                         audio.sample_rate = 44100;
                     }
-                    let new_file_name = format!("{}/{}.wav", dir, name);
+
+                    let target_sample_rate = options.sample_rate.unwrap_or(audio.sample_rate);
+                    if target_sample_rate != audio.sample_rate {
+                        audio.samples = audio.samples.iter()
+                            .map(|channel| resample::resample_channel(channel, audio.sample_rate, target_sample_rate))
+                            .collect();
+                        audio.sample_rate = target_sample_rate;
+                        audio.num_frames = audio.samples.first().map(|channel| channel.len()).unwrap_or(0);
+                    }
+
+                    audio.samples = resample::apply_channel_layout(&audio.samples, options.channels);
+                    audio.num_channels = audio.samples.len();
+
+                    if let Some(bit_depth) = options.bit_depth {
+                        let (bits_per_sample, audio_format) = bit_depth.to_format();
+                        audio.bits_per_sample = bits_per_sample;
+                        audio.audio_format = audio_format;
+                    }
+
+                    let gain_db = options.normalize_gains.as_ref().and_then(|gains| gains.get(&file).copied());
+                    if let (Some(gain_db), GainMode::Apply) = (gain_db, options.gain_mode) {
+                        loudness::apply_gain(&mut audio.samples, gain_db);
+                    }
+
+                    let stem = match &options.rewrite {
+                        Some(rewrite) => rewrite.apply(&name),
+                        None => name.clone()
+                    };
+                    let new_file_name = naming::build_path(&options.name_pattern, &dir, &stem, "wav", index);
+                    if let Some(new_file_dir) = std::path::Path::new(&new_file_name).parent() {
+                        let _ = std::fs::create_dir_all(new_file_dir);
+                    }
                     match aus::write(&new_file_name, &audio) {
-                        Ok(_) => (),
-                        Err(err) => match err {
-                            AudioError::FileCorrupt => println!("Error writing file {}: The file was corrupt.", new_file_name),
-                            AudioError::FileInaccessible(msg) => println!("Error writing file {}: The file was inaccessible ({}).", new_file_name, msg),
-                            AudioError::NumChannels(msg) => println!("Error writing file {}: The number of channels was wrong ({}).", new_file_name, msg),
-                            AudioError::NumFrames(msg) => println!("Error writing file {}: The number of frames was wrong ({}).", new_file_name, msg),
-                            AudioError::SampleValueOutOfRange(msg) => println!("Error writing file {}: A sampel value was out of range ({}).", new_file_name, msg),
-                            AudioError::WrongFormat(msg) => println!("Error writing file {}: The format was wrong ({}).", new_file_name, msg)
-                        }
+                        Ok(_) => {
+                            if !options.strip_tags {
+                                let mut tags = metadata::read(&file);
+                                if let (Some(gain_db), GainMode::Tag) = (gain_db, options.gain_mode) {
+                                    tags.replay_gain_db = Some(gain_db);
+                                }
+                                match metadata::write_wav_info_chunk(&new_file_name, &tags) {
+                                    Ok(_) => (),
+                                    Err(err) => println!("Error writing tags to {}: {}", new_file_name, err)
+                                }
+                            }
+                            let bytes_written = std::fs::metadata(&new_file_name).map(|m| m.len()).unwrap_or(0);
+                            progress.record_converted(bytes_written);
+                        },
+                        Err(err) => progress.record_failed(new_file_name, audio_error_message(&err))
                     };
                 },
-                Err(err) => match err {
-                    AudioError::FileCorrupt => println!("Error writing file {}: The file was corrupt.", file),
-                    AudioError::FileInaccessible(msg) => println!("Error writing file {}: The file was inaccessible ({}).", file, msg),
-                    AudioError::NumChannels(msg) => println!("Error writing file {}: The number of channels was wrong ({}).", file, msg),
-                    AudioError::NumFrames(msg) => println!("Error writing file {}: The number of frames was wrong ({}).", file, msg),
-                    AudioError::SampleValueOutOfRange(msg) => println!("Error writing file {}: A sampel value was out of range ({}).", file, msg),
-                    AudioError::WrongFormat(msg) => println!("Error writing file {}: The format was wrong ({}).", file, msg)
-                }
+                Err(err) => progress.record_failed(file, audio_error_message(&err))
             }
         });
     }
 
     pool.join();
+    reporter_stop.store(true, std::sync::atomic::Ordering::Relaxed);
+    let _ = reporter.join();
+    progress.into_summary()
+}
+
+/// Groups files with matching acoustic fingerprints. Each inner `Vec` holds
+/// indices into `files` that were judged to be duplicates of one another.
+/// Clustering is greedy: a file joins the first cluster whose representative
+/// (the first file added to it) it matches.
+fn find_duplicate_clusters(files: &Vec<(String, String, String)>, config: &FingerprintConfig) -> Vec<Vec<usize>> {
+    let mut fingerprints: Vec<Option<fingerprint::Fingerprint>> = Vec::with_capacity(files.len());
+    for file_tup in files.iter() {
+        let fp = match aus::read(&file_tup.0) {
+            Ok(audio) => fingerprint::fingerprint(&audio, config),
+            Err(_) => None
+        };
+        fingerprints.push(fp);
+    }
+
+    let mut clusters: Vec<Vec<usize>> = Vec::new();
+    for (i, fp) in fingerprints.iter().enumerate() {
+        let fp = match fp {
+            Some(x) => x,
+            None => continue
+        };
+        let matched_cluster = clusters.iter().position(|cluster| {
+            match &fingerprints[cluster[0]] {
+                Some(rep_fp) => fingerprint::is_duplicate(fp, rep_fp, config.match_threshold),
+                None => false
+            }
+        });
+        match matched_cluster {
+            Some(index) => clusters[index].push(i),
+            None => clusters.push(vec![i])
+        }
+    }
+
+    clusters.into_iter().filter(|c| c.len() > 1).collect()
+}
+
+/// Measures each file's integrated loudness and turns that into a per-file
+/// gain (in dB) to reach `target_lufs`. In `NormalizeMode::Album`, all files
+/// that share a parent directory (as captured by `find_audio`) are combined
+/// into one album loudness and given the same gain.
+///
+/// This runs single-threaded ahead of `process`'s worker pool and decodes
+/// every file a second time (`process` decodes it again to transcode it).
+/// Album mode genuinely needs every file's loudness gathered before any
+/// gain can be assigned, so this pre-pass can't just be folded into the
+/// pool without a barrier; it could still reuse the pool's threads rather
+/// than running serially, which would be worth revisiting if this becomes
+/// a bottleneck on large libraries.
+fn compute_normalize_gains(files: &Vec<(String, String, String)>, mode: NormalizeMode, target_lufs: f64) -> std::collections::HashMap<String, f64> {
+    let mut file_lufs: Vec<(String, String, f64)> = Vec::new();
+    for file_tup in files.iter() {
+        if let Ok(audio) = aus::read(&file_tup.0) {
+            let lufs = loudness::integrated_loudness(&audio.samples, audio.sample_rate);
+            file_lufs.push((file_tup.0.clone(), file_tup.1.clone(), lufs));
+        }
+    }
+
+    let mut gains = std::collections::HashMap::new();
+    match mode {
+        NormalizeMode::Off => (),
+        NormalizeMode::Track => {
+            for (path, _, lufs) in file_lufs.iter() {
+                gains.insert(path.clone(), loudness::gain_db(*lufs, target_lufs));
+            }
+        },
+        NormalizeMode::Album => {
+            let mut by_dir: std::collections::HashMap<String, Vec<f64>> = std::collections::HashMap::new();
+            for (_, dir, lufs) in file_lufs.iter() {
+                by_dir.entry(dir.clone()).or_insert_with(Vec::new).push(*lufs);
+            }
+            let album_lufs: std::collections::HashMap<String, f64> = by_dir.iter()
+                .map(|(dir, values)| (dir.clone(), loudness::combine_lufs(values)))
+                .collect();
+            for (path, dir, _) in file_lufs.iter() {
+                gains.insert(path.clone(), loudness::gain_db(album_lufs[dir], target_lufs));
+            }
+        }
+    }
+    gains
 }
 
+/// wavify: batch-convert an audio library to WAV, with optional duplicate
+/// detection, transcoding, loudness normalization, tag migration, and
+/// flexible output naming.
+#[derive(clap::Parser)]
+#[command(author, version, about)]
 struct Args {
+    /// Folder to scan for audio files.
+    #[arg(short = 'f', long = "folder", default_value = ".")]
     folder: String,
+
+    /// Number of worker threads to use (0 = use all available).
+    #[arg(short = 'n', long = "num-threads", default_value_t = 0)]
     num_threads: usize,
-    delete: bool
+
+    /// Delete the original files when done. With --find-duplicates, deletes
+    /// all but one copy of each duplicate found instead.
+    #[arg(short = 'd', long = "delete")]
+    delete: bool,
+
+    /// Scan for perceptually identical audio instead of converting.
+    #[arg(long = "find-duplicates")]
+    find_duplicates: bool,
+
+    #[command(flatten)]
+    fingerprint_config: FingerprintConfig,
+
+    /// Output bit depth / sample format.
+    #[arg(long = "bit-depth")]
+    bit_depth: Option<BitDepth>,
+
+    /// Output sample rate, in Hz.
+    #[arg(long = "sample-rate")]
+    sample_rate: Option<u32>,
+
+    /// Output channel layout.
+    #[arg(long = "channels", default_value = "keep")]
+    channels: ChannelLayout,
+
+    /// Skip migrating source metadata into the converted file.
+    #[arg(long = "strip-tags")]
+    strip_tags: bool,
+
+    /// Loudness-normalize the output.
+    #[arg(long = "normalize", default_value = "off")]
+    normalize: NormalizeMode,
+
+    /// Override the integrated loudness target, in LUFS (default -18,
+    /// or -23 with --loudness-preset r128).
+    #[arg(long = "target-lufs")]
+    target_lufs: Option<f64>,
+
+    /// Which default loudness target --normalize aims for when --target-lufs
+    /// isn't given: -18 LUFS (track) or -23 LUFS (EBU R128).
+    #[arg(long = "loudness-preset", default_value = "track")]
+    loudness_preset: LoudnessPreset,
+
+    /// Bake the computed gain into the samples, or store it as a tag.
+    #[arg(long = "gain-mode", default_value = "apply")]
+    gain_mode: GainMode,
+
+    /// Output path template: {parent}, {name}, {ext}, and {index} tokens.
+    #[arg(long = "name-pattern", default_value = naming::DEFAULT_PATTERN)]
+    name_pattern: String,
+
+    /// Regex applied to the file stem before templating. Requires --replace.
+    #[arg(long = "match", requires = "replace_template")]
+    match_pattern: Option<String>,
+
+    /// Replacement template for --match. Requires --match.
+    #[arg(long = "replace", requires = "match_pattern")]
+    replace_template: Option<String>,
+
+    /// Comma-separated list of extensions to scan for, overriding the built-in list.
+    #[arg(long = "ext", value_delimiter = ',')]
+    ext: Vec<String>,
+
+    /// Only scan one directory level deep instead of recursing fully.
+    #[arg(long = "no-recurse")]
+    no_recurse: bool,
+
+    /// Glob pattern of paths to skip; may be given more than once.
+    #[arg(long = "exclude")]
+    exclude: Vec<String>,
+
+    /// Print what would be converted/deleted without touching disk.
+    #[arg(long = "dry-run")]
+    dry_run: bool,
+
+    /// Emit the end-of-run summary as JSON instead of plain text.
+    #[arg(long = "json")]
+    json: bool
 }
 
-/// Validates the command line arguments
-fn validate_args(args: Vec<String>) -> Option<Args> {
-    if args.len() <= 6 {
-        let valid_args = std::collections::HashMap::from([("-f", 1), ("--folder", 1), ("-n", 1), ("--num-threads", 1), ("-d", 1), ("--delete", 1)]);
-        let mut processed_args: Args = Args{folder: String::from("."), num_threads: 0, delete: false};
-        let mut i = 1;
-        while i < args.len() {
-            if !valid_args.contains_key(args[i].as_str()) {
-                return None;
-            } else {
-                match args[i].as_str() {
-                    "-f" | "--folder" => {
-                        processed_args.folder = args[i+1].clone();
-                        i += 2;
-                    },
-                    "-n" | "--num-threads" => {
-                        processed_args.num_threads = match args[i+1].parse::<usize>() {
-                            Ok(x) => x,
-                            Err(_) => return None
+fn main() {
+    let args = Args::parse();
+
+    let extensions: Vec<String> = if args.ext.is_empty() {
+        DEFAULT_EXTENSIONS.iter().map(|e| e.to_string()).collect()
+    } else {
+        args.ext.clone()
+    };
+    let files = find_audio(&args.folder, &extensions, !args.no_recurse, &args.exclude);
+
+    if args.find_duplicates {
+        println!("Scanning {} files for duplicates...", files.len());
+        let clusters = find_duplicate_clusters(&files, &args.fingerprint_config);
+        if clusters.is_empty() {
+            println!("No duplicates found.");
+        }
+        for (cluster_num, cluster) in clusters.iter().enumerate() {
+            println!("Cluster {}:", cluster_num + 1);
+            for &index in cluster.iter() {
+                println!("  {}", files[index].0);
+            }
+            if args.delete {
+                for &index in cluster.iter().skip(1) {
+                    if args.dry_run {
+                        println!("Would delete: {}", files[index].0);
+                    } else {
+                        match std::fs::remove_file(files[index].0.as_str()) {
+                            Ok(_) => (),
+                            Err(_) => ()
                         };
-                        i += 2;
-                    },
-                    "-d" | "--delete" => {
-                        processed_args.delete = true;
-                        i += 1;
-                    }
-                    _ => {
-                        return None;
                     }
                 }
             }
         }
-
-        return Some(processed_args);
-    } else {
-        return None;
+        println!("Done.");
+        return;
     }
-}
 
-fn main() {
-    // process the arguments
-    let args = match validate_args(std::env::args().collect()) {
-        Some(x) => x,
-        None => {
-            println!("Usage:\n-f folder_name -n num_threads\nOptional: include the -d flag to delete the original files when done.");
-            return;
-        }
+    let rewrite = match (&args.match_pattern, &args.replace_template) {
+        (Some(pattern), Some(replacement)) => match Rewrite::new(pattern, replacement) {
+            Ok(rewrite) => Some(rewrite),
+            Err(err) => {
+                println!("Invalid --match pattern: {}", err);
+                return;
+            }
+        },
+        _ => None
     };
-    
+
+    if args.dry_run {
+        println!("Would convert {} files:", files.len());
+        for (index, file_tup) in files.iter().enumerate() {
+            let stem = match &rewrite {
+                Some(rewrite) => rewrite.apply(&file_tup.2),
+                None => file_tup.2.clone()
+            };
+            let new_file_name = naming::build_path(&args.name_pattern, &file_tup.1, &stem, "wav", index);
+            println!("  {} -> {}", file_tup.0, new_file_name);
+        }
+        if args.delete {
+            println!("Would delete {} original files.", files.len());
+        }
+        println!("Done (dry run).");
+        return;
+    }
+
     // convert the files
-    let files = find_audio(&args.folder);
     println!("Converting {} files...", files.len());
-    process(&files, args.num_threads);
+    let normalize_gains = match args.normalize {
+        NormalizeMode::Off => None,
+        mode => {
+            let target_lufs = args.target_lufs.unwrap_or(args.loudness_preset.target_lufs());
+            println!("Analyzing loudness...");
+            Some(std::sync::Arc::new(compute_normalize_gains(&files, mode, target_lufs)))
+        }
+    };
+    let transcode_options = TranscodeOptions {
+        bit_depth: args.bit_depth,
+        sample_rate: args.sample_rate,
+        channels: args.channels,
+        strip_tags: args.strip_tags,
+        normalize_gains,
+        gain_mode: args.gain_mode,
+        name_pattern: args.name_pattern,
+        rewrite
+    };
+    let summary = process(&files, args.num_threads, transcode_options);
 
     // delete the old files if asked to
     if args.delete {
@@ -170,5 +466,11 @@ fn main() {
             };
         }
     }
+
+    if args.json {
+        println!("{}", summary.to_json());
+    } else {
+        summary.print();
+    }
     println!("Done.");
 }
@@ -0,0 +1,141 @@
+//! Reading and migrating metadata tags between audio files.
+//!
+//! `aus` only round-trips PCM samples, so tag extraction leans on
+//! format-specific readers, and the written-out side patches the WAV file
+//! in place with a RIFF `LIST INFO` chunk: that's a plain chunk append,
+//! not something `aus::write` needs to know about.
+
+use id3::TagLike;
+
+/// The common subset of tags we migrate across formats.
+#[derive(Clone, Default)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub year: Option<String>,
+    pub genre: Option<String>,
+    pub track_number: Option<String>,
+    pub replay_gain_db: Option<f64>
+}
+
+/// Reads whatever tags are available from the source file, based on its
+/// extension. Files with no recognized tag block return an empty `Metadata`.
+pub fn read(path: &str) -> Metadata {
+    let extension = std::path::Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    match extension.as_str() {
+        "mp3" => read_id3(path),
+        "flac" => read_vorbis_comments(path),
+        "m4a" => read_mp4_tags(path),
+        _ => Metadata::default()
+    }
+}
+
+fn read_id3(path: &str) -> Metadata {
+    match id3::Tag::read_from_path(path) {
+        Ok(tag) => Metadata {
+            title: tag.title().map(String::from),
+            artist: tag.artist().map(String::from),
+            album: tag.album().map(String::from),
+            album_artist: tag.album_artist().map(String::from),
+            year: tag.year().map(|y| y.to_string()),
+            genre: tag.genre().map(String::from),
+            track_number: tag.track().map(|t| t.to_string()),
+            replay_gain_db: None
+        },
+        Err(_) => Metadata::default()
+    }
+}
+
+fn read_vorbis_comments(path: &str) -> Metadata {
+    match metaflac::Tag::read_from_path(path) {
+        Ok(tag) => {
+            let comments = tag.vorbis_comments();
+            Metadata {
+                title: comments.and_then(|c| c.title()).and_then(|v| v.first()).cloned(),
+                artist: comments.and_then(|c| c.artist()).and_then(|v| v.first()).cloned(),
+                album: comments.and_then(|c| c.album()).and_then(|v| v.first()).cloned(),
+                album_artist: comments.and_then(|c| c.get("ALBUMARTIST")).and_then(|v| v.first()).cloned(),
+                year: comments.and_then(|c| c.get("DATE")).and_then(|v| v.first()).cloned(),
+                genre: comments.and_then(|c| c.get("GENRE")).and_then(|v| v.first()).cloned(),
+                track_number: comments.and_then(|c| c.track()).map(|t| t.to_string()),
+                replay_gain_db: None
+            }
+        },
+        Err(_) => Metadata::default()
+    }
+}
+
+fn read_mp4_tags(path: &str) -> Metadata {
+    match mp4ameta::Tag::read_from_path(path) {
+        Ok(tag) => Metadata {
+            title: tag.title().map(String::from),
+            artist: tag.artist().map(String::from),
+            album: tag.album().map(String::from),
+            album_artist: tag.album_artist().map(String::from),
+            year: tag.year().map(String::from),
+            genre: tag.genre().map(String::from),
+            track_number: tag.track_number().map(|n| n.to_string()),
+            replay_gain_db: None
+        },
+        Err(_) => Metadata::default()
+    }
+}
+
+/// Writes `metadata` into a RIFF `LIST INFO` chunk appended to an existing
+/// WAV file at `path`, patching the RIFF header's size field to match.
+pub fn write_wav_info_chunk(path: &str, metadata: &Metadata) -> std::io::Result<()> {
+    let mut entries: Vec<(&[u8; 4], &String)> = Vec::new();
+    if let Some(title) = &metadata.title { entries.push((b"INAM", title)); }
+    if let Some(artist) = &metadata.artist { entries.push((b"IART", artist)); }
+    if let Some(album) = &metadata.album { entries.push((b"IPRD", album)); }
+    if let Some(album_artist) = &metadata.album_artist { entries.push((b"IAAR", album_artist)); }
+    if let Some(year) = &metadata.year { entries.push((b"ICRD", year)); }
+    if let Some(genre) = &metadata.genre { entries.push((b"IGNR", genre)); }
+    if let Some(track_number) = &metadata.track_number { entries.push((b"ITRK", track_number)); }
+    let replay_gain_text = metadata.replay_gain_db.map(|db| format!("{:.2} dB", db));
+    if let Some(replay_gain_text) = &replay_gain_text { entries.push((b"IRGN", replay_gain_text)); }
+
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let mut list_body: Vec<u8> = b"INFO".to_vec();
+    for (chunk_id, value) in entries.iter() {
+        let mut text = value.as_bytes().to_vec();
+        text.push(0);
+        if text.len() % 2 != 0 {
+            text.push(0);
+        }
+        list_body.extend_from_slice(*chunk_id);
+        list_body.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        list_body.extend_from_slice(&text);
+    }
+
+    let mut list_chunk: Vec<u8> = b"LIST".to_vec();
+    list_chunk.extend_from_slice(&(list_body.len() as u32).to_le_bytes());
+    list_chunk.extend_from_slice(&list_body);
+    if list_chunk.len() % 2 != 0 {
+        list_chunk.push(0);
+    }
+
+    use std::io::{Read, Seek, SeekFrom, Write};
+    let mut file = std::fs::OpenOptions::new().read(true).write(true).open(path)?;
+    let mut header = [0u8; 8];
+    file.read_exact(&mut header)?;
+    let riff_size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+
+    file.seek(SeekFrom::End(0))?;
+    file.write_all(&list_chunk)?;
+
+    let new_riff_size = riff_size + list_chunk.len() as u32;
+    file.seek(SeekFrom::Start(4))?;
+    file.write_all(&new_riff_size.to_le_bytes())?;
+
+    Ok(())
+}
@@ -0,0 +1,76 @@
+//! Builds output file paths from a user-supplied naming template instead of
+//! the fixed `"{parent}/{name}.wav"` scheme, optionally rewriting the file
+//! stem with a regex find/replace first.
+
+use regex::Regex;
+
+/// The default naming template, matching wavify's original hard-coded
+/// output path.
+pub const DEFAULT_PATTERN: &str = "{parent}/{name}.wav";
+
+/// A compiled find/replace pair applied to the file stem before templating.
+#[derive(Clone)]
+pub struct Rewrite {
+    pattern: Regex,
+    replacement: String
+}
+
+impl Rewrite {
+    pub fn new(pattern: &str, replacement: &str) -> Result<Rewrite, regex::Error> {
+        Ok(Rewrite { pattern: Regex::new(pattern)?, replacement: replacement.to_string() })
+    }
+
+    pub fn apply(&self, stem: &str) -> String {
+        self.pattern.replace_all(stem, self.replacement.as_str()).into_owned()
+    }
+}
+
+/// Builds an output path from `pattern`, substituting the `{parent}`,
+/// `{name}`, `{ext}`, and `{index}` tokens.
+pub fn build_path(pattern: &str, parent: &str, name: &str, ext: &str, index: usize) -> String {
+    pattern
+        .replace("{parent}", parent)
+        .replace("{name}", name)
+        .replace("{ext}", ext)
+        .replace("{index}", &index.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_path_substitutes_every_token() {
+        let path = build_path("{parent}/{name}_{index}.{ext}", "dir", "song", "wav", 3);
+        assert_eq!(path, "dir/song_3.wav");
+    }
+
+    #[test]
+    fn build_path_matches_the_default_pattern() {
+        let path = build_path(DEFAULT_PATTERN, "dir", "song", "wav", 0);
+        assert_eq!(path, "dir/song.wav");
+    }
+
+    #[test]
+    fn build_path_leaves_unknown_tokens_untouched() {
+        let path = build_path("{parent}/{unknown}.{ext}", "dir", "song", "wav", 0);
+        assert_eq!(path, "dir/{unknown}.wav");
+    }
+
+    #[test]
+    fn rewrite_applies_a_regex_replacement_to_the_stem() {
+        let rewrite = Rewrite::new(r"^\d+ - ", "").unwrap();
+        assert_eq!(rewrite.apply("03 - Track Name"), "Track Name");
+    }
+
+    #[test]
+    fn rewrite_leaves_non_matching_stems_untouched() {
+        let rewrite = Rewrite::new(r"^\d+ - ", "").unwrap();
+        assert_eq!(rewrite.apply("Track Name"), "Track Name");
+    }
+
+    #[test]
+    fn rewrite_rejects_an_invalid_pattern() {
+        assert!(Rewrite::new("(", "").is_err());
+    }
+}
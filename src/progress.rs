@@ -0,0 +1,155 @@
+//! Shared concurrent progress tracking for a conversion batch: atomic
+//! counters updated from each pooled worker, a background reporter thread
+//! that prints a periodic one-line status, and a structured end-of-run
+//! summary (plain text or JSON) once the pool has drained.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How often the background reporter thread prints a status line.
+const REPORT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// One file that failed to convert, paired with the error message that
+/// caused it.
+pub struct Failure {
+    pub file: String,
+    pub message: String
+}
+
+/// Atomic counters shared across the worker pool, plus the list of
+/// failures accumulated as they occur. Cheap to clone (it's an `Arc`
+/// underneath) so every pooled closure can hold its own handle.
+#[derive(Clone)]
+pub struct Progress {
+    inner: Arc<ProgressInner>
+}
+
+struct ProgressInner {
+    discovered: u64,
+    converted: AtomicU64,
+    skipped: AtomicU64,
+    failed: AtomicU64,
+    bytes_written: AtomicU64,
+    failures: Mutex<Vec<Failure>>
+}
+
+impl Progress {
+    pub fn new(discovered: u64) -> Progress {
+        Progress {
+            inner: Arc::new(ProgressInner {
+                discovered,
+                converted: AtomicU64::new(0),
+                skipped: AtomicU64::new(0),
+                failed: AtomicU64::new(0),
+                bytes_written: AtomicU64::new(0),
+                failures: Mutex::new(Vec::new())
+            })
+        }
+    }
+
+    pub fn record_converted(&self, bytes_written: u64) {
+        self.inner.converted.fetch_add(1, Ordering::Relaxed);
+        self.inner.bytes_written.fetch_add(bytes_written, Ordering::Relaxed);
+    }
+
+    pub fn record_skipped(&self) {
+        self.inner.skipped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_failed(&self, file: String, message: String) {
+        self.inner.failed.fetch_add(1, Ordering::Relaxed);
+        self.inner.failures.lock().unwrap().push(Failure { file, message });
+    }
+
+    /// A short status line, e.g. `"312/5000 converted, 4 errors"`.
+    pub fn status_line(&self) -> String {
+        format!(
+            "{}/{} converted, {} errors",
+            self.inner.converted.load(Ordering::Relaxed),
+            self.inner.discovered,
+            self.inner.failed.load(Ordering::Relaxed)
+        )
+    }
+
+    /// Spawns a background thread that prints `status_line()` every
+    /// `REPORT_INTERVAL` until `stop` is set, then prints one final line.
+    /// Returns the thread's `JoinHandle` so the caller can wait for it to
+    /// notice `stop` and exit before reporting the final summary.
+    pub fn spawn_reporter(&self, stop: Arc<AtomicBool>) -> std::thread::JoinHandle<()> {
+        let progress = self.clone();
+        std::thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                println!("{}", progress.status_line());
+                std::thread::sleep(REPORT_INTERVAL);
+            }
+            println!("{}", progress.status_line());
+        })
+    }
+
+    /// Consumes the tracker into a final, immutable `Summary` once the pool
+    /// has drained and the reporter thread has stopped.
+    pub fn into_summary(self) -> Summary {
+        let inner = match Arc::try_unwrap(self.inner) {
+            Ok(inner) => inner,
+            Err(arc) => {
+                return Summary {
+                    discovered: arc.discovered,
+                    converted: arc.converted.load(Ordering::Relaxed),
+                    skipped: arc.skipped.load(Ordering::Relaxed),
+                    failed: arc.failed.load(Ordering::Relaxed),
+                    bytes_written: arc.bytes_written.load(Ordering::Relaxed),
+                    failures: std::mem::take(&mut *arc.failures.lock().unwrap())
+                };
+            }
+        };
+        Summary {
+            discovered: inner.discovered,
+            converted: inner.converted.load(Ordering::Relaxed),
+            skipped: inner.skipped.load(Ordering::Relaxed),
+            failed: inner.failed.load(Ordering::Relaxed),
+            bytes_written: inner.bytes_written.load(Ordering::Relaxed),
+            failures: inner.failures.into_inner().unwrap()
+        }
+    }
+}
+
+/// A structured tally of a finished conversion batch, suitable for either a
+/// human-readable report or `--json` output.
+pub struct Summary {
+    pub discovered: u64,
+    pub converted: u64,
+    pub skipped: u64,
+    pub failed: u64,
+    pub bytes_written: u64,
+    pub failures: Vec<Failure>
+}
+
+impl Summary {
+    /// Prints the human-readable end-of-run report: the overall tally,
+    /// followed by every failed file and the error that caused it.
+    pub fn print(&self) {
+        println!(
+            "{} discovered, {} converted, {} skipped, {} failed, {} bytes written",
+            self.discovered, self.converted, self.skipped, self.failed, self.bytes_written
+        );
+        for failure in self.failures.iter() {
+            println!("  FAILED: {}: {}", failure.file, failure.message);
+        }
+    }
+
+    /// Renders the summary as a single-line JSON object, for `--json`.
+    pub fn to_json(&self) -> String {
+        let failures: Vec<String> = self.failures.iter()
+            .map(|f| format!("{{\"file\":\"{}\",\"message\":\"{}\"}}", json_escape(&f.file), json_escape(&f.message)))
+            .collect();
+        format!(
+            "{{\"discovered\":{},\"converted\":{},\"skipped\":{},\"failed\":{},\"bytes_written\":{},\"failures\":[{}]}}",
+            self.discovered, self.converted, self.skipped, self.failed, self.bytes_written, failures.join(",")
+        )
+    }
+}
+
+fn json_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}